@@ -0,0 +1,187 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use crate::{
+    evm::user::UserOutcomeKind,
+    pricing::{Gas, Ink},
+    Bytes20, Bytes32,
+};
+use eyre::Result;
+use std::fmt;
+
+/// A read-only view of data returned by the host, such as return data or account code.
+pub trait DataReader: Clone + Send + 'static {
+    fn slice(&self) -> &[u8];
+}
+
+/// The hostio requests a Stylus program may issue against the EVM it's executing within.
+pub trait EvmApi<D: DataReader>: Send + 'static {
+    fn get_bytes32(&mut self, key: Bytes32) -> (Bytes32, Ink);
+    fn cache_bytes32(&mut self, key: Bytes32, value: Bytes32) -> Ink;
+    /// Flushes dirty slots to the host. If the host runs out of gas partway through, the
+    /// returned error downcasts to a [`StorageFlushOutOfGas`] carrying the accurate cost of
+    /// the slots that *were* committed.
+    fn flush_storage_cache(&mut self, clear: bool, gas_left: Gas) -> Result<Ink>;
+    fn contract_call(
+        &mut self,
+        contract: Bytes20,
+        input: &[u8],
+        gas: Gas,
+        value: Bytes32,
+    ) -> (u32, Ink, UserOutcomeKind);
+    fn delegate_call(
+        &mut self,
+        contract: Bytes20,
+        input: &[u8],
+        gas: Gas,
+    ) -> (u32, Ink, UserOutcomeKind);
+    fn static_call(
+        &mut self,
+        contract: Bytes20,
+        input: &[u8],
+        gas: Gas,
+    ) -> (u32, Ink, UserOutcomeKind);
+    fn create1(
+        &mut self,
+        code: Vec<u8>,
+        endowment: Bytes32,
+        gas: Gas,
+    ) -> (Result<Bytes20>, u32, Ink);
+    fn create2(
+        &mut self,
+        code: Vec<u8>,
+        endowment: Bytes32,
+        salt: Bytes32,
+        gas: Gas,
+    ) -> (Result<Bytes20>, u32, Ink);
+    fn get_return_data(&self) -> D;
+    fn emit_log(&mut self, data: Vec<u8>, topics: u32) -> Result<()>;
+    fn account_balance(&mut self, address: Bytes20) -> (Bytes32, Ink);
+    fn account_code(&mut self, address: Bytes20, gas_left: Gas) -> (D, Ink);
+    fn account_codehash(&mut self, address: Bytes20) -> (Bytes32, Ink);
+    fn add_pages(&mut self, pages: u16) -> Ink;
+    fn capture_hostio(
+        &mut self,
+        name: &str,
+        args: &[u8],
+        outs: &[u8],
+        start_ink: Ink,
+        end_ink: Ink,
+    );
+}
+
+/// The hostio methods an [`EvmApi`] may issue to its [`RequestHandler`](super::req::RequestHandler).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EvmApiMethod {
+    GetBytes32,
+    SetTrieSlots,
+    ContractCall,
+    DelegateCall,
+    StaticCall,
+    Create1,
+    Create2,
+    EmitLog,
+    AccountBalance,
+    AccountCode,
+    AccountCodeHash,
+    AddPages,
+    CaptureHostIO,
+}
+
+/// The status byte a host response is tagged with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EvmApiStatus {
+    Success,
+    Failure,
+    /// The host ran out of gas partway through a [`EvmApiMethod::SetTrieSlots`] batch. The
+    /// response carries a `u32` count of how many of the slots (in the order they were sent)
+    /// were actually committed before the host stopped.
+    OutOfGas,
+}
+
+impl From<EvmApiStatus> for u8 {
+    fn from(value: EvmApiStatus) -> Self {
+        value as u8
+    }
+}
+
+impl TryFrom<u8> for EvmApiStatus {
+    type Error = eyre::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => Self::Success,
+            1 => Self::Failure,
+            2 => Self::OutOfGas,
+            _ => eyre::bail!("unknown EvmApiStatus {value}"),
+        })
+    }
+}
+
+/// Returned (via downcast of the [`EvmApi::flush_storage_cache`] error) when the host ran out
+/// of gas partway through a storage flush, so the caller can still account for the gas spent
+/// on the slots that were actually committed.
+#[derive(Debug)]
+pub struct StorageFlushOutOfGas {
+    /// How many of the dirty slots, in the deterministic order they were sent, were
+    /// actually committed before the host stopped.
+    pub committed: usize,
+    /// How many dirty slots were in the flush batch altogether.
+    pub total: usize,
+    /// The accurate gas cost of the slots that were committed, already charged to the caller.
+    pub cost: Ink,
+}
+
+impl fmt::Display for StorageFlushOutOfGas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "out of gas flushing storage cache: committed {} of {} slots",
+            self.committed, self.total,
+        )
+    }
+}
+
+impl std::error::Error for StorageFlushOutOfGas {}
+
+/// The status byte a host response to a call or create request is tagged with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EvmApiCallStatus {
+    Success,
+    Revert,
+    Failure,
+    OutOfGas,
+    /// The callee attempted to modify state (`SSTORE`, `CREATE`, `LOG*`, ...) from within a
+    /// `static_call` frame.
+    WriteProtection,
+}
+
+impl TryFrom<u8> for EvmApiCallStatus {
+    type Error = eyre::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => Self::Success,
+            1 => Self::Revert,
+            2 => Self::Failure,
+            3 => Self::OutOfGas,
+            4 => Self::WriteProtection,
+            _ => eyre::bail!("unknown call status {value}"),
+        })
+    }
+}
+
+impl From<EvmApiCallStatus> for UserOutcomeKind {
+    fn from(status: EvmApiCallStatus) -> Self {
+        match status {
+            EvmApiCallStatus::Success => Self::Success,
+            EvmApiCallStatus::Revert => Self::Revert,
+            EvmApiCallStatus::Failure => Self::Failure,
+            EvmApiCallStatus::OutOfGas => Self::OutOfGas,
+            EvmApiCallStatus::WriteProtection => Self::WriteProtection,
+        }
+    }
+}
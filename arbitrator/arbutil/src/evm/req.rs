@@ -3,85 +3,158 @@
 
 use crate::{
     evm::{
-        api::{DataReader, EvmApi, EvmApiMethod, EvmApiStatus},
+        api::{
+            DataReader, EvmApi, EvmApiCallStatus, EvmApiMethod, EvmApiStatus, StorageFlushOutOfGas,
+        },
+        cache::LruCache,
         storage::{StorageCache, StorageWord},
         user::UserOutcomeKind,
     },
     format::Utf8OrHex,
-    pricing::EVM_API_INK,
+    pricing::{Gas, Ink, EVM_API_INK, INK_PRICE},
     Bytes20, Bytes32,
 };
 use eyre::{bail, eyre, Result};
 use std::collections::hash_map::Entry;
 
 pub trait RequestHandler<D: DataReader>: Send + 'static {
-    fn handle_request(&mut self, req_type: EvmApiMethod, req_data: &[u8]) -> (Vec<u8>, D, u64);
+    fn handle_request(
+        &mut self,
+        req_type: EvmApiMethod,
+        req_data: impl AsRef<[u8]>,
+    ) -> (Vec<u8>, D, Gas);
+
+    /// A scatter-gather variant for hostios whose payload is naturally split across multiple
+    /// borrowed buffers, such as `emit_log`'s topics header and log data. The default impl
+    /// copies the parts into one `Vec`, same as calling `handle_request` directly. A
+    /// `RequestHandler` backed by a real I/O boundary can override it to write each part
+    /// directly (e.g. via `writev`) and avoid that copy; no such override exists in this crate.
+    fn handle_request_vectored(
+        &mut self,
+        req_type: EvmApiMethod,
+        parts: &[&[u8]],
+    ) -> (Vec<u8>, D, Gas) {
+        let mut combined = Vec::with_capacity(parts.iter().map(|part| part.len()).sum());
+        for part in parts {
+            combined.extend_from_slice(part);
+        }
+        self.handle_request(req_type, combined)
+    }
 }
 
 pub struct EvmApiRequestor<D: DataReader, H: RequestHandler<D>> {
     handler: H,
-    last_code: Option<(Bytes20, D)>,
+    code_cache: LruCache<Bytes20, D>,
     last_return_data: Option<D>,
     storage_cache: StorageCache,
+    /// Whether the current frame is a `static_call`, in which state-modifying hostios
+    /// (`SSTORE`, `CREATE`, `LOG*`) must be rejected rather than forwarded to the host.
+    is_static: bool,
+    /// A reusable buffer for encoding request payloads, cleared (not reallocated) between
+    /// requests so hot paths like repeated `contract_call`s stop churning the allocator.
+    scratch: Vec<u8>,
 }
 
 impl<D: DataReader, H: RequestHandler<D>> EvmApiRequestor<D, H> {
-    pub fn new(handler: H) -> Self {
+    pub fn new(handler: H, is_static: bool, code_cache_capacity: usize) -> Self {
         Self {
             handler,
-            last_code: None,
+            code_cache: LruCache::new(code_cache_capacity),
             last_return_data: None,
             storage_cache: StorageCache::default(),
+            is_static,
+            scratch: Vec::new(),
         }
     }
 
-    fn handle_request(&mut self, req_type: EvmApiMethod, req_data: &[u8]) -> (Vec<u8>, D, u64) {
+    /// Returns a write-protection error for a state-modifying hostio attempted in a static frame.
+    fn write_protection_error(op: &str) -> eyre::Error {
+        eyre!("{op}: {:?}", UserOutcomeKind::WriteProtection)
+    }
+
+    fn handle_request(
+        &mut self,
+        req_type: EvmApiMethod,
+        req_data: impl AsRef<[u8]>,
+    ) -> (Vec<u8>, D, Gas) {
         self.handler.handle_request(req_type, req_data)
     }
 
+    /// Encodes a request into the reusable scratch buffer and dispatches it, without
+    /// allocating a fresh `Vec` per call.
+    fn request(
+        &mut self,
+        req_type: EvmApiMethod,
+        encode: impl FnOnce(&mut Vec<u8>),
+    ) -> (Vec<u8>, D, Gas) {
+        self.scratch.clear();
+        encode(&mut self.scratch);
+        self.handler.handle_request(req_type, &self.scratch)
+    }
+
     /// Call out to a contract.
     fn call_request(
         &mut self,
         call_type: EvmApiMethod,
         contract: Bytes20,
         input: &[u8],
-        gas: u64,
+        gas: Gas,
         value: Bytes32,
-    ) -> (u32, u64, UserOutcomeKind) {
-        let mut request = Vec::with_capacity(20 + 32 + 8 + input.len());
-        request.extend(contract);
-        request.extend(value);
-        request.extend(gas.to_be_bytes());
-        request.extend(input);
-
-        let (res, data, cost) = self.handle_request(call_type, &request);
-        let status: UserOutcomeKind = res[0].try_into().expect("unknown outcome");
+    ) -> (u32, Ink, UserOutcomeKind) {
+        let (res, data, cost) = self.request(call_type, |request| {
+            request.extend(contract);
+            request.extend(value);
+            request.extend(gas.to_be_bytes());
+            request.extend(input);
+        });
+        let status = match res.first().copied().map(EvmApiCallStatus::try_from) {
+            Some(Ok(status)) => UserOutcomeKind::from(status),
+            _ => UserOutcomeKind::Failure,
+        };
         let data_len = data.slice().len() as u32;
         self.last_return_data = Some(data);
-        (data_len, cost, status)
+        (data_len, cost.gas_to_ink(INK_PRICE), status)
     }
 
     pub fn request_handler(&mut self) -> &mut H {
         &mut self.handler
     }
 
+    /// Seeds the EIP-2929 access list for an access-list transaction, marking `keys` and
+    /// `addresses` as warm before execution begins.
+    pub fn prewarm_access_list(
+        &mut self,
+        keys: impl IntoIterator<Item = Bytes32>,
+        addresses: impl IntoIterator<Item = Bytes20>,
+    ) {
+        for key in keys {
+            self.storage_cache.prewarm_slot(key);
+        }
+        for address in addresses {
+            self.storage_cache.prewarm_account(address);
+        }
+    }
+
     fn create_request(
         &mut self,
         create_type: EvmApiMethod,
         code: Vec<u8>,
         endowment: Bytes32,
         salt: Option<Bytes32>,
-        gas: u64,
-    ) -> (Result<Bytes20>, u32, u64) {
-        let mut request = Vec::with_capacity(8 + 2 * 32 + code.len());
-        request.extend(gas.to_be_bytes());
-        request.extend(endowment);
-        if let Some(salt) = salt {
-            request.extend(salt);
+        gas: Gas,
+    ) -> (Result<Bytes20>, u32, Ink) {
+        if self.is_static {
+            return (Err(Self::write_protection_error("create")), 0, Ink(0));
         }
-        request.extend(code);
-
-        let (mut res, data, cost) = self.handle_request(create_type, &request);
+        let (mut res, data, cost) = self.request(create_type, |request| {
+            request.extend(gas.to_be_bytes());
+            request.extend(endowment);
+            if let Some(salt) = salt {
+                request.extend(salt);
+            }
+            request.extend(code);
+        });
+        let cost = cost.gas_to_ink(INK_PRICE);
         if res.len() != 21 || res[0] == 0 {
             if !res.is_empty() {
                 res.remove(0);
@@ -90,7 +163,9 @@ impl<D: DataReader, H: RequestHandler<D>> EvmApiRequestor<D, H> {
             return (Err(eyre!(err_string)), 0, cost);
         }
         res.remove(0);
-        let address = res.try_into().unwrap();
+        let address: Bytes20 = res.try_into().unwrap();
+        // The deploy just overwrote this address's code, so any cached copy is now stale.
+        self.code_cache.remove(&address);
         let data_len = data.slice().len() as u32;
         self.last_return_data = Some(data);
         (Ok(address), data_len, cost)
@@ -98,57 +173,106 @@ impl<D: DataReader, H: RequestHandler<D>> EvmApiRequestor<D, H> {
 }
 
 impl<D: DataReader, H: RequestHandler<D>> EvmApi<D> for EvmApiRequestor<D, H> {
-    fn get_bytes32(&mut self, key: Bytes32) -> (Bytes32, u64) {
+    fn get_bytes32(&mut self, key: Bytes32) -> (Bytes32, Ink) {
         let cache = &mut self.storage_cache;
-        let mut cost = cache.read_gas();
+        let mut gas_cost = cache.access_slot_gas(key);
 
         let value = cache.entry(key).or_insert_with(|| {
             let (res, _, gas) = self
                 .handler
                 .handle_request(EvmApiMethod::GetBytes32, key.as_slice());
-            cost = cost.saturating_add(gas).saturating_add(EVM_API_INK);
+            gas_cost = gas_cost.saturating_add(gas);
             StorageWord::known(res.try_into().unwrap())
         });
+        let cost = gas_cost.gas_to_ink(INK_PRICE).saturating_add(EVM_API_INK);
         (value.value, cost)
     }
 
-    fn cache_bytes32(&mut self, key: Bytes32, value: Bytes32) -> u64 {
+    fn cache_bytes32(&mut self, key: Bytes32, value: Bytes32) -> Ink {
+        let access_gas = self.storage_cache.access_slot_gas(key);
         match self.storage_cache.entry(key) {
             Entry::Occupied(mut key) => key.get_mut().value = value,
             Entry::Vacant(slot) => drop(slot.insert(StorageWord::unknown(value))),
         };
-        self.storage_cache.write_gas()
+        (access_gas + self.storage_cache.write_gas()).gas_to_ink(INK_PRICE)
     }
 
-    fn flush_storage_cache(&mut self, clear: bool, gas_left: u64) -> Result<u64> {
-        let mut data = Vec::with_capacity(64 * self.storage_cache.len() + 8);
-        data.extend(gas_left.to_be_bytes());
+    fn flush_storage_cache(&mut self, clear: bool, gas_left: Gas) -> Result<Ink> {
+        if self.is_static && self.storage_cache.slots.values().any(|word| word.dirty()) {
+            return Err(Self::write_protection_error("flush_storage_cache"));
+        }
 
-        for (key, value) in &mut self.storage_cache.slots {
-            if value.dirty() {
-                data.extend(*key);
-                data.extend(*value.value);
-                value.known = Some(value.value);
-            }
+        // Flush in a stable, sorted-by-key order so that when the host can only afford to
+        // commit a prefix of the batch, that prefix is reproducible across proving and
+        // execution (a non-deterministic `HashMap` iteration order would not be).
+        let mut dirty: Vec<Bytes32> = self
+            .storage_cache
+            .slots
+            .iter()
+            .filter(|(_, word)| word.dirty())
+            .map(|(key, _)| *key)
+            .collect();
+        dirty.sort_unstable();
+
+        self.scratch.clear();
+        self.scratch.reserve(64 * dirty.len() + 8);
+        self.scratch.extend(gas_left.to_be_bytes());
+        for key in &dirty {
+            let word = &self.storage_cache.slots[key];
+            self.scratch.extend(*key);
+            self.scratch.extend(*word.value);
         }
-        if clear {
-            self.storage_cache.clear();
+
+        let (res, _, cost) = self
+            .handler
+            .handle_request(EvmApiMethod::SetTrieSlots, &self.scratch);
+        let cost = cost.gas_to_ink(INK_PRICE);
+
+        if res.is_empty() {
+            bail!("malformed response flushing storage cache: empty status");
         }
 
-        let (res, _, cost) = self.handle_request(EvmApiMethod::SetTrieSlots, &data);
-        if res[0] != EvmApiStatus::Success.into() {
-            bail!("{}", String::from_utf8_or_hex(res));
+        match EvmApiStatus::try_from(res[0]) {
+            Ok(EvmApiStatus::Success) => {
+                for key in &dirty {
+                    let word = self.storage_cache.slots.get_mut(key).unwrap();
+                    word.known = Some(word.value);
+                }
+                if clear {
+                    self.storage_cache.clear();
+                }
+                Ok(cost)
+            }
+            Ok(EvmApiStatus::OutOfGas) => {
+                if res.len() < 5 {
+                    bail!("malformed out-of-gas response flushing storage cache");
+                }
+                let committed =
+                    (u32::from_be_bytes(res[1..5].try_into().unwrap()) as usize).min(dirty.len());
+                for key in &dirty[..committed] {
+                    let word = self.storage_cache.slots.get_mut(key).unwrap();
+                    word.known = Some(word.value);
+                }
+                return Err(StorageFlushOutOfGas {
+                    committed,
+                    total: dirty.len(),
+                    cost,
+                }
+                .into());
+            }
+            Ok(EvmApiStatus::Failure) | Err(_) => {
+                bail!("{}", String::from_utf8_or_hex(res));
+            }
         }
-        Ok(cost)
     }
 
     fn contract_call(
         &mut self,
         contract: Bytes20,
         input: &[u8],
-        gas: u64,
+        gas: Gas,
         value: Bytes32,
-    ) -> (u32, u64, UserOutcomeKind) {
+    ) -> (u32, Ink, UserOutcomeKind) {
         self.call_request(EvmApiMethod::ContractCall, contract, input, gas, value)
     }
 
@@ -156,8 +280,8 @@ impl<D: DataReader, H: RequestHandler<D>> EvmApi<D> for EvmApiRequestor<D, H> {
         &mut self,
         contract: Bytes20,
         input: &[u8],
-        gas: u64,
-    ) -> (u32, u64, UserOutcomeKind) {
+        gas: Gas,
+    ) -> (u32, Ink, UserOutcomeKind) {
         self.call_request(
             EvmApiMethod::DelegateCall,
             contract,
@@ -171,8 +295,8 @@ impl<D: DataReader, H: RequestHandler<D>> EvmApi<D> for EvmApiRequestor<D, H> {
         &mut self,
         contract: Bytes20,
         input: &[u8],
-        gas: u64,
-    ) -> (u32, u64, UserOutcomeKind) {
+        gas: Gas,
+    ) -> (u32, Ink, UserOutcomeKind) {
         self.call_request(
             EvmApiMethod::StaticCall,
             contract,
@@ -186,8 +310,8 @@ impl<D: DataReader, H: RequestHandler<D>> EvmApi<D> for EvmApiRequestor<D, H> {
         &mut self,
         code: Vec<u8>,
         endowment: Bytes32,
-        gas: u64,
-    ) -> (Result<Bytes20>, u32, u64) {
+        gas: Gas,
+    ) -> (Result<Bytes20>, u32, Ink) {
         self.create_request(EvmApiMethod::Create1, code, endowment, None, gas)
     }
 
@@ -196,8 +320,8 @@ impl<D: DataReader, H: RequestHandler<D>> EvmApi<D> for EvmApiRequestor<D, H> {
         code: Vec<u8>,
         endowment: Bytes32,
         salt: Bytes32,
-        gas: u64,
-    ) -> (Result<Bytes20>, u32, u64) {
+        gas: Gas,
+    ) -> (Result<Bytes20>, u32, Ink) {
         self.create_request(EvmApiMethod::Create2, code, endowment, Some(salt), gas)
     }
 
@@ -206,46 +330,57 @@ impl<D: DataReader, H: RequestHandler<D>> EvmApi<D> for EvmApiRequestor<D, H> {
     }
 
     fn emit_log(&mut self, data: Vec<u8>, topics: u32) -> Result<()> {
-        // TODO: remove copy
-        let mut request = Vec::with_capacity(4 + data.len());
-        request.extend(topics.to_be_bytes());
-        request.extend(data);
-
-        let (res, _, _) = self.handle_request(EvmApiMethod::EmitLog, &request);
+        if self.is_static {
+            return Err(Self::write_protection_error("emit_log"));
+        }
+        let topics = topics.to_be_bytes();
+        let (res, _, _) = self
+            .handler
+            .handle_request_vectored(EvmApiMethod::EmitLog, &[topics.as_slice(), data.as_slice()]);
         if !res.is_empty() {
             bail!(String::from_utf8(res).unwrap_or("malformed emit-log response".into()))
         }
         Ok(())
     }
 
-    fn account_balance(&mut self, address: Bytes20) -> (Bytes32, u64) {
+    fn account_balance(&mut self, address: Bytes20) -> (Bytes32, Ink) {
+        let access_gas = self.storage_cache.access_account_gas(address);
         let (res, _, cost) = self.handle_request(EvmApiMethod::AccountBalance, address.as_slice());
-        (res.try_into().unwrap(), cost)
+        (
+            res.try_into().unwrap(),
+            (access_gas + cost).gas_to_ink(INK_PRICE),
+        )
     }
 
-    fn account_code(&mut self, address: Bytes20, gas_left: u64) -> (D, u64) {
-        if let Some((stored_address, data)) = self.last_code.as_ref() {
-            if address == *stored_address {
-                return (data.clone(), 0);
-            }
+    fn account_code(&mut self, address: Bytes20, gas_left: Gas) -> (D, Ink) {
+        if let Some(data) = self.code_cache.get(&address) {
+            // Already fetched and still fresh: no host round-trip, but the EIP-2929
+            // warm/cold account charge still applies, same as any other account access.
+            let access_gas = self.storage_cache.access_account_gas(address);
+            return (data.clone(), access_gas.gas_to_ink(INK_PRICE));
         }
-        let mut req = Vec::with_capacity(20 + 8);
-        req.extend(address);
-        req.extend(gas_left.to_be_bytes());
-
-        let (_, data, cost) = self.handle_request(EvmApiMethod::AccountCode, &req);
-        self.last_code = Some((address, data.clone()));
-        (data, cost)
+        let access_gas = self.storage_cache.access_account_gas(address);
+        let (_, data, cost) = self.request(EvmApiMethod::AccountCode, |request| {
+            request.extend(address);
+            request.extend(gas_left.to_be_bytes());
+        });
+        self.code_cache.insert(address, data.clone());
+        (data, (access_gas + cost).gas_to_ink(INK_PRICE))
     }
 
-    fn account_codehash(&mut self, address: Bytes20) -> (Bytes32, u64) {
+    fn account_codehash(&mut self, address: Bytes20) -> (Bytes32, Ink) {
+        let access_gas = self.storage_cache.access_account_gas(address);
         let (res, _, cost) = self.handle_request(EvmApiMethod::AccountCodeHash, address.as_slice());
-        (res.try_into().unwrap(), cost)
+        (
+            res.try_into().unwrap(),
+            (access_gas + cost).gas_to_ink(INK_PRICE),
+        )
     }
 
-    fn add_pages(&mut self, pages: u16) -> u64 {
+    fn add_pages(&mut self, pages: u16) -> Ink {
         self.handle_request(EvmApiMethod::AddPages, &pages.to_be_bytes())
             .2
+            .gas_to_ink(INK_PRICE)
     }
 
     fn capture_hostio(
@@ -253,18 +388,125 @@ impl<D: DataReader, H: RequestHandler<D>> EvmApi<D> for EvmApiRequestor<D, H> {
         name: &str,
         args: &[u8],
         outs: &[u8],
-        start_ink: u64,
-        end_ink: u64,
+        start_ink: Ink,
+        end_ink: Ink,
     ) {
-        let mut request = Vec::with_capacity(2 * 8 + 3 * 2 + name.len() + args.len() + outs.len());
-        request.extend(start_ink.to_be_bytes());
-        request.extend(end_ink.to_be_bytes());
-        request.extend((name.len() as u16).to_be_bytes());
-        request.extend((args.len() as u16).to_be_bytes());
-        request.extend((outs.len() as u16).to_be_bytes());
-        request.extend(name.as_bytes());
-        request.extend(args);
-        request.extend(outs);
-        self.handle_request(EvmApiMethod::CaptureHostIO, &request);
+        self.request(EvmApiMethod::CaptureHostIO, |request| {
+            request.extend(start_ink.to_be_bytes());
+            request.extend(end_ink.to_be_bytes());
+            request.extend((name.len() as u16).to_be_bytes());
+            request.extend((args.len() as u16).to_be_bytes());
+            request.extend((outs.len() as u16).to_be_bytes());
+            request.extend(name.as_bytes());
+            request.extend(args);
+            request.extend(outs);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestData(Vec<u8>);
+
+    impl DataReader for TestData {
+        fn slice(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    /// A `RequestHandler` that always answers with a canned response, for exercising
+    /// `EvmApiRequestor` without a real host.
+    struct StubHandler {
+        response: Vec<u8>,
+        cost: Gas,
+    }
+
+    impl RequestHandler<TestData> for StubHandler {
+        fn handle_request(
+            &mut self,
+            _req_type: EvmApiMethod,
+            _req_data: impl AsRef<[u8]>,
+        ) -> (Vec<u8>, TestData, Gas) {
+            (self.response.clone(), TestData(Vec::new()), self.cost)
+        }
+    }
+
+    fn key(byte: u8) -> Bytes32 {
+        vec![byte; 32].try_into().unwrap()
+    }
+
+    fn out_of_gas_response(committed: u32) -> Vec<u8> {
+        let mut res = vec![EvmApiStatus::OutOfGas.into()];
+        res.extend(committed.to_be_bytes());
+        res
+    }
+
+    #[test]
+    fn flush_storage_cache_marks_only_the_committed_prefix_clean() {
+        let handler = StubHandler {
+            response: out_of_gas_response(1),
+            cost: Gas(42),
+        };
+        let mut requestor = EvmApiRequestor::new(handler, false, 0);
+        // Sorted by key, so key(1) is flushed before key(2); the host reports only the
+        // first as committed.
+        requestor.cache_bytes32(key(1), key(1));
+        requestor.cache_bytes32(key(2), key(2));
+
+        let err = requestor
+            .flush_storage_cache(false, Gas(1_000))
+            .unwrap_err();
+        let out_of_gas = err.downcast_ref::<StorageFlushOutOfGas>().unwrap();
+        assert_eq!(out_of_gas.committed, 1);
+        assert_eq!(out_of_gas.total, 2);
+        assert_eq!(out_of_gas.cost, Gas(42).gas_to_ink(INK_PRICE));
+
+        assert!(!requestor.storage_cache.slots[&key(1)].dirty());
+        assert!(requestor.storage_cache.slots[&key(2)].dirty());
+    }
+
+    #[test]
+    fn flush_storage_cache_reports_a_committed_count_past_the_batch_as_fully_committed() {
+        // A malformed or confused host claiming more commits than were sent shouldn't panic
+        // on the slice that marks slots clean.
+        let handler = StubHandler {
+            response: out_of_gas_response(99),
+            cost: Gas(0),
+        };
+        let mut requestor = EvmApiRequestor::new(handler, false, 0);
+        requestor.cache_bytes32(key(1), key(1));
+
+        let err = requestor.flush_storage_cache(false, Gas(0)).unwrap_err();
+        let out_of_gas = err.downcast_ref::<StorageFlushOutOfGas>().unwrap();
+        assert_eq!(out_of_gas.committed, 1);
+        assert_eq!(out_of_gas.total, 1);
+    }
+
+    #[test]
+    fn flush_storage_cache_rejects_an_empty_response() {
+        let handler = StubHandler {
+            response: Vec::new(),
+            cost: Gas(0),
+        };
+        let mut requestor = EvmApiRequestor::new(handler, false, 0);
+        requestor.cache_bytes32(key(1), key(1));
+
+        assert!(requestor.flush_storage_cache(false, Gas(0)).is_err());
+    }
+
+    #[test]
+    fn flush_storage_cache_rejects_a_truncated_out_of_gas_response() {
+        let handler = StubHandler {
+            response: vec![EvmApiStatus::OutOfGas.into(), 0, 0],
+            cost: Gas(0),
+        };
+        let mut requestor = EvmApiRequestor::new(handler, false, 0);
+        requestor.cache_bytes32(key(1), key(1));
+
+        let err = requestor.flush_storage_cache(false, Gas(0)).unwrap_err();
+        assert!(err.downcast_ref::<StorageFlushOutOfGas>().is_none());
     }
 }
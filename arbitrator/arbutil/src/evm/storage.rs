@@ -0,0 +1,160 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use crate::{pricing::Gas, Bytes20, Bytes32};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+/// The EIP-2929 gas charged for the first `SLOAD`/`SSTORE` of a slot in a transaction.
+pub const COLD_SLOAD_GAS: Gas = Gas(2100);
+
+/// The EIP-2929 gas charged for subsequent accesses to an already-warm slot.
+pub const WARM_SLOAD_GAS: Gas = Gas(100);
+
+/// The gas charged for every `SSTORE`, on top of the slot's warm/cold access cost.
+pub const STORAGE_WRITE_GAS: Gas = Gas(100);
+
+/// The EIP-2929 gas charged for the first touch of an account in a transaction
+/// (`BALANCE`, `EXTCODESIZE`/`EXTCODECOPY`, `EXTCODEHASH`). Calls and creates do not
+/// charge it here.
+pub const COLD_ACCOUNT_GAS: Gas = Gas(2600);
+
+/// The gas charged for subsequent accesses to an already-warm account.
+pub const WARM_ACCOUNT_GAS: Gas = Gas(100);
+
+/// A cached storage slot, possibly dirty with respect to the value on chain.
+#[derive(Clone, Copy, Debug)]
+pub struct StorageWord {
+    /// The up-to-date value of the slot.
+    pub value: Bytes32,
+    /// The value of the slot as last observed on chain, if any.
+    pub known: Option<Bytes32>,
+}
+
+impl StorageWord {
+    /// Constructs a word whose on-chain value is known (e.g. freshly loaded from the host).
+    pub fn known(value: Bytes32) -> Self {
+        Self {
+            value,
+            known: Some(value),
+        }
+    }
+
+    /// Constructs a word that hasn't yet been confirmed on chain (e.g. a speculative write).
+    pub fn unknown(value: Bytes32) -> Self {
+        Self { value, known: None }
+    }
+
+    /// Whether this slot's value diverges from the last known on-chain value.
+    pub fn dirty(&self) -> bool {
+        self.known != Some(self.value)
+    }
+}
+
+/// A per-transaction cache of storage slots and EIP-2929 warm/cold access state.
+///
+/// Warmth is tracked independently of caching: `warm_slots` records every key that's been
+/// touched (including ones prewarmed from an access list but never actually read), while
+/// `slots` only holds keys whose value has actually been fetched from or written to the
+/// host. A key can be warm without being in `slots`, but never the other way around. Both
+/// sets are reset when the cache is cleared.
+#[derive(Default)]
+pub struct StorageCache {
+    pub slots: HashMap<Bytes32, StorageWord>,
+    warm_slots: HashSet<Bytes32>,
+    pub warm_accounts: HashSet<Bytes20>,
+}
+
+impl StorageCache {
+    pub fn entry(&mut self, key: Bytes32) -> Entry<'_, Bytes32, StorageWord> {
+        self.warm_slots.insert(key);
+        self.slots.entry(key)
+    }
+
+    /// Returns the EIP-2929 access cost for `key`, marking it warm for the rest of the cache's
+    /// lifetime.
+    pub fn access_slot_gas(&mut self, key: Bytes32) -> Gas {
+        match self.warm_slots.insert(key) {
+            true => COLD_SLOAD_GAS,
+            false => WARM_SLOAD_GAS,
+        }
+    }
+
+    pub fn write_gas(&self) -> Gas {
+        STORAGE_WRITE_GAS
+    }
+
+    /// Returns the EIP-2929 access cost for `address`, marking it warm for the rest of the
+    /// cache's lifetime.
+    pub fn access_account_gas(&mut self, address: Bytes20) -> Gas {
+        match self.warm_accounts.insert(address) {
+            true => COLD_ACCOUNT_GAS,
+            false => WARM_ACCOUNT_GAS,
+        }
+    }
+
+    /// Marks a storage slot as warm without fetching or caching its value, as when seeding
+    /// an access-list transaction before execution begins.
+    pub fn prewarm_slot(&mut self, key: Bytes32) {
+        self.warm_slots.insert(key);
+    }
+
+    /// Marks an account as warm, as when seeding an access-list transaction before execution
+    /// begins.
+    pub fn prewarm_account(&mut self, address: Bytes20) {
+        self.warm_accounts.insert(address);
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.warm_slots.clear();
+        self.warm_accounts.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> Bytes32 {
+        vec![byte; 32].try_into().unwrap()
+    }
+
+    #[test]
+    fn access_slot_gas_charges_cold_once_then_warm() {
+        let mut cache = StorageCache::default();
+        let key = key(1);
+        assert_eq!(cache.access_slot_gas(key), COLD_SLOAD_GAS);
+        assert_eq!(cache.access_slot_gas(key), WARM_SLOAD_GAS);
+        assert_eq!(cache.access_slot_gas(key), WARM_SLOAD_GAS);
+    }
+
+    #[test]
+    fn prewarm_slot_marks_warm_without_caching_a_value() {
+        let mut cache = StorageCache::default();
+        let key = key(2);
+        cache.prewarm_slot(key);
+
+        // Warmth applies even though the slot was never fetched or written...
+        assert_eq!(cache.access_slot_gas(key), WARM_SLOAD_GAS);
+        // ...and `slots` stays empty, so a later `entry()` still sees a vacant slot rather
+        // than a fabricated value.
+        assert!(cache.slots.is_empty());
+        assert!(matches!(cache.entry(key), Entry::Vacant(_)));
+    }
+
+    #[test]
+    fn entry_marks_the_key_warm() {
+        let mut cache = StorageCache::default();
+        let key = key(3);
+        cache.entry(key).or_insert_with(|| StorageWord::known(key));
+        assert_eq!(cache.access_slot_gas(key), WARM_SLOAD_GAS);
+    }
+}
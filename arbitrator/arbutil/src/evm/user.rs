@@ -0,0 +1,15 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+/// The result of a call, delegate-call, static-call, or create made by a Stylus contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum UserOutcomeKind {
+    Success,
+    Revert,
+    Failure,
+    OutOfInk,
+    OutOfGas,
+    /// The callee attempted a state-modifying operation while inside a `static_call`.
+    WriteProtection,
+}
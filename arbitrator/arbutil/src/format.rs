@@ -0,0 +1,17 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+/// Renders a byte slice as UTF-8 if it's valid, falling back to hex otherwise.
+/// Useful for turning host-provided error payloads into something printable.
+pub trait Utf8OrHex {
+    fn from_utf8_or_hex(self) -> String;
+}
+
+impl Utf8OrHex for Vec<u8> {
+    fn from_utf8_or_hex(self) -> String {
+        match String::from_utf8(self) {
+            Ok(text) => text,
+            Err(err) => format!("0x{}", hex::encode(err.into_bytes())),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2023-2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use std::ops::{Add, AddAssign, Mul, Sub, SubAssign};
+
+/// Generates the arithmetic a gas/ink newtype needs to be used like a number
+/// without ever being mistaken for the other unit.
+macro_rules! derive_math {
+    ($name:ident) => {
+        impl $name {
+            pub const fn add(self, other: Self) -> Self {
+                Self(self.0 + other.0)
+            }
+
+            pub const fn sub(self, other: Self) -> Self {
+                Self(self.0 - other.0)
+            }
+
+            pub const fn saturating_add(self, other: Self) -> Self {
+                Self(self.0.saturating_add(other.0))
+            }
+
+            pub const fn saturating_sub(self, other: Self) -> Self {
+                Self(self.0.saturating_sub(other.0))
+            }
+
+            pub const fn to_be_bytes(self) -> [u8; 8] {
+                self.0.to_be_bytes()
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, other: Self) -> Self {
+                Self(self.0 + other.0)
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: Self) {
+                self.0 += other.0;
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, other: Self) -> Self {
+                Self(self.0 - other.0)
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, other: Self) {
+                self.0 -= other.0;
+            }
+        }
+
+        impl Mul<u64> for $name {
+            type Output = Self;
+
+            fn mul(self, other: u64) -> Self {
+                Self(self.0 * other)
+            }
+        }
+    };
+}
+
+/// An amount of EVM gas, the unit the go-ethereum state transition charges in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Gas(pub u64);
+
+derive_math!(Gas);
+
+/// An amount of Stylus ink, the finer-grained unit WASM execution is metered in.
+/// One gas is worth [`INK_PRICE`] ink.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ink(pub u64);
+
+derive_math!(Ink);
+
+impl Gas {
+    /// Converts this amount of gas to its ink-equivalent at the given ink price.
+    pub const fn gas_to_ink(self, ink_price: u64) -> Ink {
+        Ink(self.0.saturating_mul(ink_price))
+    }
+}
+
+impl Ink {
+    /// Converts this amount of ink to its gas-equivalent at the given ink price.
+    pub const fn ink_to_gas(self, ink_price: u64) -> Gas {
+        Gas(self.0 / ink_price)
+    }
+}
+
+/// The number of ink units a gas unit is worth.
+pub const INK_PRICE: u64 = 10_000;
+
+/// The ink cost of an `EvmApi` hostio request, charged in addition to whatever
+/// EVM gas the request itself consumes.
+pub const EVM_API_INK: Ink = Ink(4 * INK_PRICE);